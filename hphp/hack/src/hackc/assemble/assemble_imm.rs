@@ -4,6 +4,7 @@
 // LICENSE file in the "hack" directory of this source tree.
 
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use assemble_opcode_macro::assemble_imm_for_enum;
 use bumpalo::Bump;
@@ -13,6 +14,7 @@ use hhbc::StringId;
 
 use crate::assemble;
 use crate::assemble::DeclMap;
+use crate::diagnostic::Diagnostic;
 use crate::lexer::Lexer;
 use crate::token::Token;
 
@@ -209,7 +211,19 @@ impl<'arena> AssembleImm<'arena, hhbc::AdataId> for Lexer<'_> {
     fn assemble_imm(&mut self, _: &'arena Bump, _: &DeclMap) -> Result<hhbc::AdataId> {
         let adata_id = self.expect_with(Token::into_global)?;
         debug_assert!(adata_id[0] == b'@');
-        Ok(hhbc::AdataId::parse(std::str::from_utf8(&adata_id[1..])?)?)
+        let name = std::str::from_utf8(&adata_id[1..])?;
+        // The common case is the numeric `A_<n>` spelling `AdataId::parse`
+        // understands directly; anything else is a user-chosen symbolic
+        // label (`.adata my_array = ...` referenced as `@my_array`), which
+        // stays valid across edits that insert/delete other `.adata`
+        // entries and so is resolved through the unit's adata label table
+        // instead of a fixed index.
+        match hhbc::AdataId::parse(name) {
+            Ok(id) => Ok(id),
+            Err(_) => Ok(hhbc::AdataId::new(
+                self.adata_labels().resolve(name)? as usize
+            )),
+        }
     }
 }
 
@@ -254,8 +268,189 @@ impl<'arena> AssembleImm<'arena, hhbc::FCallArgs> for Lexer<'_> {
 
 impl AssembleImm<'_, hhbc::FloatBits> for Lexer<'_> {
     fn assemble_imm(&mut self, _: &'_ Bump, _: &DeclMap) -> Result<hhbc::FloatBits> {
-        Ok(hhbc::FloatBits(self.expect_and_get_number()?))
+        // Unlike a negative decimal/hex literal -- which the lexer folds the
+        // '-' into as a single number token, per `is_hex_float`/
+        // `parse_hex_float`'s own `strip_prefix('-')` handling below -- `-`
+        // immediately followed by the letter `inf` doesn't match the number
+        // grammar at all, so the lexer hands it back as its own token. Peel
+        // it off explicitly instead of expecting it fused onto `inf`.
+        let negated = self.peek_is(Token::is_minus);
+        if negated {
+            self.expect(Token::is_minus)?;
+        }
+        let tok = self.expect_token()?;
+        let value = match tok.into_identifier() {
+            Ok(b"inf") => {
+                if negated {
+                    f64::NEG_INFINITY
+                } else {
+                    f64::INFINITY
+                }
+            }
+            Ok(b"nan") if !negated => f64::NAN,
+            // A non-canonical NaN (a custom/signaling payload, or one with
+            // the sign bit set) can't be denoted by the bare `nan` keyword,
+            // which `print_float_bits` only emits for the single canonical
+            // quiet-NaN bit pattern; every other NaN is printed as `nan`
+            // followed directly by its full 64-bit pattern in hex, which is
+            // parsed back here instead of going through `round_to_f64` (a
+            // NaN's biased exponent is out of that function's finite range).
+            Ok(ident) if !negated && ident.len() > 3 && ident.starts_with(b"nan") => {
+                let hex = std::str::from_utf8(&ident[3..])
+                    .with_context(|| format!("non-ASCII nan payload: {:?}", ident))?;
+                let raw = u64::from_str_radix(hex, 16)
+                    .with_context(|| format!("invalid nan payload: {:?}", ident))?;
+                let value = f64::from_bits(raw);
+                if !value.is_nan() {
+                    bail!("nan payload bits {:#x} don't actually encode a NaN", raw);
+                }
+                value
+            }
+            Ok(_) => return Err(tok.error("Expected a double, hex float, inf, -inf, or nan")),
+            Err(_) if !negated => {
+                let raw = tok.into_number()?;
+                let s = std::str::from_utf8(raw)?;
+                if is_hex_float(s) {
+                    parse_hex_float(s).with_context(|| format!("Malformed hex float: {:?}", s))?
+                } else {
+                    s.parse::<f64>()
+                        .with_context(|| format!("Malformed double: {:?}", s))?
+                }
+            }
+            Err(_) => return Err(tok.error("Expected 'inf' after '-'")),
+        };
+        Ok(hhbc::FloatBits(value))
+    }
+}
+
+/// True for `[-]0x...p...` tokens, the C99 hexadecimal float grammar.
+fn is_hex_float(s: &str) -> bool {
+    s.strip_prefix('-').unwrap_or(s).to_ascii_lowercase().starts_with("0x")
+}
+
+/// Parse a C99 hex float literal of the form `[-]0x<hexint>[.<hexfrac>]p[+/-]<decexp>`
+/// into the exact `f64` it denotes, bit-for-bit. Unlike decimal literals, every
+/// finite `f64` (including subnormals and signed zero) has a hex float spelling
+/// that round-trips exactly, which is why the disassembler emits this form.
+fn parse_hex_float(s: &str) -> Result<f64> {
+    let (neg, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let rest = rest
+        .strip_prefix("0x")
+        .or_else(|| rest.strip_prefix("0X"))
+        .ok_or_else(|| anyhow::anyhow!("hex float must start with 0x: {:?}", s))?;
+    let (mantissa, exp) = rest
+        .split_once(['p', 'P'])
+        .ok_or_else(|| anyhow::anyhow!("hex float is missing its binary exponent: {:?}", s))?;
+    let exp: i64 = exp
+        .parse()
+        .with_context(|| format!("invalid hex float exponent: {:?}", s))?;
+    let (int_digits, frac_digits) = match mantissa.split_once('.') {
+        Some((i, f)) => (i, f),
+        None => (mantissa, ""),
+    };
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        bail!("hex float has no digits: {:?}", s);
+    }
+    if !int_digits
+        .bytes()
+        .chain(frac_digits.bytes())
+        .all(|b| b.is_ascii_hexdigit())
+    {
+        bail!("hex float has a non-hex digit: {:?}", s);
+    }
+
+    // Fold every hex digit into a 128-bit significand (far more precision than
+    // an f64 can ever use), remembering in `sticky` whether any digit beyond
+    // that was nonzero so later rounding stays round-to-nearest-even exact.
+    const KEPT_HEX_DIGITS: usize = 32;
+    let mut bits: u128 = 0;
+    let mut sticky = false;
+    for (i, c) in int_digits.bytes().chain(frac_digits.bytes()).enumerate() {
+        let digit = (c as char).to_digit(16).unwrap() as u128;
+        if i < KEPT_HEX_DIGITS {
+            bits = (bits << 4) | digit;
+        } else {
+            sticky |= digit != 0;
+        }
     }
+    if bits == 0 {
+        return Ok(if neg { -0.0 } else { 0.0 });
+    }
+
+    let used_digits = int_digits.len() + frac_digits.len();
+    let dropped_digits = used_digits.saturating_sub(KEPT_HEX_DIGITS);
+    let binary_exp = exp - (frac_digits.len() as i64) * 4 + (dropped_digits as i64) * 4;
+
+    let msb = 127 - bits.leading_zeros() as i64;
+    let unbiased_exp = msb + binary_exp;
+    Ok(round_to_f64(neg, bits, msb, unbiased_exp, sticky))
+}
+
+/// Round a normalized significand (`bits`, whose top set bit is at index
+/// `msb`) representing a number with unbiased base-2 exponent `unbiased_exp`
+/// to the nearest `f64`, ties to even, honoring `sticky` bits already
+/// dropped upstream.
+fn round_to_f64(neg: bool, bits: u128, msb: i64, unbiased_exp: i64, sticky: bool) -> f64 {
+    const BIAS: i64 = 1023;
+    if unbiased_exp > BIAS {
+        return if neg { f64::NEG_INFINITY } else { f64::INFINITY };
+    }
+    if unbiased_exp < -(BIAS - 1) - 52 - 1 {
+        return if neg { -0.0 } else { 0.0 };
+    }
+
+    // Normal numbers keep 53 significant bits (1 implicit + 52 stored);
+    // subnormals keep fewer, trading significand bits for a wider exponent.
+    let kept = if unbiased_exp >= -(BIAS - 1) {
+        53
+    } else {
+        53 + (unbiased_exp - (-(BIAS - 1)))
+    };
+    let kept = kept.clamp(0, 53);
+
+    let shift = msb - (kept - 1);
+    let mut mantissa = if shift <= 0 {
+        bits << (-shift)
+    } else {
+        let kept_bits = bits >> shift;
+        let round_bit = (bits >> (shift - 1)) & 1;
+        let lower_mask = (1u128 << (shift - 1)) - 1;
+        let lower_sticky = sticky || (bits & lower_mask) != 0;
+        if round_bit == 1 && (lower_sticky || kept_bits & 1 == 1) {
+            kept_bits + 1
+        } else {
+            kept_bits
+        }
+    };
+
+    // Subnormals are stored with a biased exponent field of 0 (and no
+    // implicit leading bit), not `unbiased_exp + BIAS` -- which for a
+    // subnormal's negative `unbiased_exp` wraps around to a huge `u64` once
+    // cast below and corrupts the sign/exponent bits entirely.
+    let mut exp_field = if kept < 53 { 0 } else { unbiased_exp + BIAS };
+    if kept < 53 {
+        // Subnormal: no implicit leading bit: store `kept` raw fraction bits,
+        // counted in units of the smallest subnormal (2^-1074).
+        if mantissa == (1u128 << 52) {
+            // Rounded up into the smallest normal number.
+            exp_field = 1;
+        }
+    } else if mantissa == (1u128 << 53) {
+        // Rounded up and carried out of the mantissa: bump the exponent.
+        mantissa >>= 1;
+        exp_field += 1;
+    }
+
+    if exp_field >= 0x7ff {
+        return if neg { f64::NEG_INFINITY } else { f64::INFINITY };
+    }
+
+    let fraction = (mantissa as u64) & ((1u64 << 52) - 1);
+    let sign_bit = if neg { 1u64 << 63 } else { 0 };
+    f64::from_bits(sign_bit | ((exp_field as u64) << 52) | fraction)
 }
 
 impl<'arena> AssembleImm<'arena, hhbc::FunctionName> for Lexer<'_> {
@@ -318,6 +513,11 @@ impl AssembleImm<'_, hhbc::Local> for Lexer<'_> {
                 let v = hhbc::intern(std::str::from_utf8(v)?);
                 if let Some(idx) = decl_map.get(&v) {
                     Ok(hhbc::Local { idx: *idx })
+                } else if self.is_recovering() {
+                    let diagnostic =
+                        Diagnostic::at(self, p.offset(), "a declared local", format!("${}", v.as_str()));
+                    self.record_diagnostic(diagnostic);
+                    Ok(hhbc::Local::INVALID)
                 } else {
                     bail!("Unknown local var: {:?} at {:?}", v, p);
                 }
@@ -328,6 +528,16 @@ impl AssembleImm<'_, hhbc::Local> for Lexer<'_> {
                     idx: std::str::from_utf8(&i[1..i.len()])?.parse()?,
                 })
             }
+            Some(tok) if self.is_recovering() => {
+                let diagnostic = Diagnostic::at(
+                    self,
+                    tok.offset(),
+                    "a local variable or _N index",
+                    tok.describe(),
+                );
+                self.record_diagnostic(diagnostic);
+                Ok(hhbc::Local::INVALID)
+            }
             Some(tok) => Err(tok.error("Unknown local")),
             None => Err(self.error("Expected local")),
         }
@@ -335,9 +545,15 @@ impl AssembleImm<'_, hhbc::Local> for Lexer<'_> {
 }
 
 impl AssembleImm<'_, hhbc::LocalRange> for Lexer<'_> {
-    fn assemble_imm(&mut self, _: &'_ Bump, _: &DeclMap) -> Result<hhbc::LocalRange> {
+    fn assemble_imm(&mut self, _: &'_ Bump, decl_map: &DeclMap) -> Result<hhbc::LocalRange> {
         self.expect_str(Token::is_identifier, "L")?;
         self.expect(Token::is_colon)?;
+        // Named span, e.g. `L:$first..$last`, resolved endpoint-by-endpoint
+        // through decl_map -- easier to hand-write/read than counting out a
+        // numeric `start len` for something like MemoGet/SetRangeM.
+        if self.peek_is(Token::is_variable) {
+            return assemble_named_local_range(self, decl_map);
+        }
         let start = hhbc::Local {
             idx: self.expect_and_get_number()?,
         };
@@ -347,6 +563,42 @@ impl AssembleImm<'_, hhbc::LocalRange> for Lexer<'_> {
     }
 }
 
+/// Resolve `$first..$last` (the `L:` and its colon already consumed) into a
+/// `LocalRange`, requiring both endpoints to name declared locals and the
+/// range they span to be non-empty and in order.
+fn assemble_named_local_range(lexer: &mut Lexer<'_>, decl_map: &DeclMap) -> Result<hhbc::LocalRange> {
+    let first_idx = assemble_named_local_endpoint(lexer, decl_map)?;
+    lexer.expect(Token::is_dot_dot)?;
+    let last_idx = assemble_named_local_endpoint(lexer, decl_map)?;
+    if last_idx < first_idx {
+        bail!(
+            "Named local range ${}..${} is backwards: {} > {}",
+            first_idx,
+            last_idx,
+            first_idx,
+            last_idx
+        );
+    }
+    Ok(hhbc::LocalRange {
+        start: hhbc::Local { idx: first_idx },
+        len: last_idx - first_idx + 1,
+    })
+}
+
+fn assemble_named_local_endpoint(lexer: &mut Lexer<'_>, decl_map: &DeclMap) -> Result<u32> {
+    match lexer.next() {
+        Some(Token::Variable(v, p)) => {
+            let v = hhbc::intern(std::str::from_utf8(v)?);
+            decl_map
+                .get(&v)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("Unknown local var in named range: {:?} at {:?}", v, p))
+        }
+        Some(tok) => Err(tok.error("Expected a named local in range")),
+        None => Err(lexer.error("Expected a named local in range")),
+    }
+}
+
 impl<'arena> AssembleImm<'arena, hhbc::MemberKey> for Lexer<'_> {
     fn assemble_imm(&mut self, alloc: &'arena Bump, decl_map: &DeclMap) -> Result<hhbc::MemberKey> {
         // EC: stackIndex readOnlyOp | EL: local readOnlyOp | ET: string readOnlyOp | EI: int readOnlyOp
@@ -413,6 +665,17 @@ impl<'arena> AssembleImm<'arena, hhbc::MemberKey> for Lexer<'_> {
                 ))
             }
             b"W" => Ok(hhbc::MemberKey::W),
+            _ if self.is_recovering() => {
+                let diagnostic = Diagnostic::at(
+                    self,
+                    tok.offset(),
+                    "a MemberKey (EC:/EL:/ET:/EI:/PC:/PL:/PT:/QT:/W)",
+                    tok.describe(),
+                );
+                self.record_diagnostic(diagnostic);
+                self.skip_to_instruction_boundary();
+                Ok(hhbc::MemberKey::W)
+            }
             _ => Err(tok.error("Expected a MemberKey")),
         }
     }
@@ -487,3 +750,98 @@ impl AssembleImm<'_, hhbc::SwitchKind> for Lexer<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::assemble::DeclMap;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn hex_float_round_trips_bit_patterns() {
+        let cases: &[f64] = &[
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            std::f64::consts::PI,
+            f64::MIN_POSITIVE,
+            f64::MIN_POSITIVE / 2.0, // subnormal
+            f64::MAX,
+            5e-324, // smallest subnormal
+        ];
+        for &value in cases {
+            let text = crate::print::format_hex_float(value);
+            assert_eq!(
+                parse_hex_float(&text).unwrap().to_bits(),
+                value.to_bits(),
+                "round-trip of {:?} via {:?}",
+                value,
+                text,
+            );
+        }
+    }
+
+    #[test]
+    fn hex_float_rejects_decimal_and_garbage() {
+        assert!(!is_hex_float("1.5"));
+        assert!(!is_hex_float("-1.5"));
+        assert!(is_hex_float("0x1.8p3"));
+        assert!(is_hex_float("-0x1p0"));
+        assert!(parse_hex_float("0x").is_err());
+        assert!(parse_hex_float("0x1.8").is_err());
+    }
+
+    #[test]
+    fn float_bits_round_trips_via_print_float_bits() {
+        let cases: &[f64] = &[
+            0.0,
+            -0.0,
+            1.5,
+            std::f64::consts::PI,
+            1e300,
+            f64::MIN_POSITIVE / 2.0, // subnormal
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        for &value in cases {
+            let mut buf = Vec::new();
+            crate::print::print_float_bits(&mut buf, hhbc::FloatBits(value)).unwrap();
+            let alloc = Bump::new();
+            let decl_map = DeclMap::default();
+            let mut lexer = Lexer::from_bytes(&buf);
+            let parsed: hhbc::FloatBits = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+            assert_eq!(parsed.0.to_bits(), value.to_bits(), "round-trip of {value:?}");
+        }
+        assert!(
+            crate::print::print_float_bits(&mut Vec::new(), hhbc::FloatBits(f64::NAN)).is_ok()
+        );
+    }
+
+    #[test]
+    fn nan_payloads_round_trip_through_print_float_bits() {
+        // A signaling NaN, a custom non-canonical payload, and a negative
+        // (sign-bit-set) NaN all have bit patterns the bare `nan` keyword
+        // can't denote -- only the canonical quiet NaN can.
+        let canonical = f64::NAN.to_bits();
+        let cases: &[u64] = &[
+            canonical,
+            canonical | 1,                // custom payload
+            0x7ff0_0000_0000_0001,        // signaling NaN
+            canonical | (1u64 << 63),     // sign bit set
+        ];
+        for &raw in cases {
+            let value = f64::from_bits(raw);
+            assert!(value.is_nan());
+            let mut buf = Vec::new();
+            crate::print::print_float_bits(&mut buf, hhbc::FloatBits(value)).unwrap();
+            let alloc = Bump::new();
+            let decl_map = DeclMap::default();
+            let mut lexer = Lexer::from_bytes(&buf);
+            let parsed: hhbc::FloatBits = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+            assert_eq!(parsed.0.to_bits(), raw, "round-trip of nan bits {:#x}", raw);
+        }
+    }
+}
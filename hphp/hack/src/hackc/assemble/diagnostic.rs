@@ -0,0 +1,103 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! Structured diagnostics for recovering-mode assembly.
+//!
+//! In strict mode a malformed token (`bail!("Unknown local var...")`,
+//! `tok.error("Expected a MemberKey")`) aborts the whole parse. In recovering
+//! mode the `Lexer` instead records one of these and skips ahead to the next
+//! instruction/statement boundary, so tooling can report every problem in a
+//! hand-edited (or adversarial) `.hhas` file in a single pass.
+
+use crate::lexer::Lexer;
+
+/// One malformed-input diagnostic recorded while assembling in recovering
+/// mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Byte offset into the source where the problem was found.
+    pub offset: usize,
+    /// What the parser was expecting at `offset`.
+    pub expected: String,
+    /// What it found instead.
+    pub found: String,
+    /// The instruction or directive the parser was inside when it gave up,
+    /// e.g. `"MemoGet"` or `".function foo"`, if known.
+    pub enclosing_instruction: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(offset: usize, expected: impl Into<String>, found: impl Into<String>) -> Self {
+        Self {
+            offset,
+            expected: expected.into(),
+            found: found.into(),
+            enclosing_instruction: None,
+        }
+    }
+
+    pub fn with_enclosing_instruction(mut self, name: impl Into<String>) -> Self {
+        self.enclosing_instruction = Some(name.into());
+        self
+    }
+
+    /// Construct a diagnostic at `offset`, attaching whatever instruction
+    /// or directive `lexer` was inside when it gave up (if the
+    /// recovering-mode driver is currently tracking one), via
+    /// [`Self::with_enclosing_instruction`]. Every recovering-mode
+    /// diagnostic site should go through this rather than bare [`Self::new`]
+    /// so `enclosing_instruction` doesn't silently stay `None`.
+    pub fn at(
+        lexer: &Lexer<'_>,
+        offset: usize,
+        expected: impl Into<String>,
+        found: impl Into<String>,
+    ) -> Self {
+        let diagnostic = Self::new(offset, expected, found);
+        match lexer.enclosing_instruction() {
+            Some(name) => diagnostic.with_enclosing_instruction(name),
+            None => diagnostic,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at byte {}: expected {}, found {}",
+            self.offset, self.expected, self.found
+        )?;
+        if let Some(enclosing) = &self.enclosing_instruction {
+            write!(f, " (in {})", enclosing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where `assemble_imm` impls push a [`Diagnostic`] instead of bailing when
+/// the `Lexer` is in recovering mode.
+#[derive(Debug, Default)]
+pub struct DiagnosticSink {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! The disassembler side of the textual HHAS grammar that `assemble_imm.rs`
+//! parses. Every function here must emit exactly the spelling the matching
+//! `AssembleImm` impl accepts, so that `assemble(disassemble(unit)) ==
+//! unit`. The grammar is otherwise only implicit in the parser; this module
+//! is where it's pinned down in the other direction.
+
+use std::io::Write;
+
+use hhbc::FloatBits;
+use hhbc::IterArgs;
+use hhbc::Local;
+use hhbc::LocalRange;
+use hhbc::MemberKey;
+
+use crate::symbol_resolver::LabelResolver;
+
+/// Print an `@name` reference to the entry at `idx` in `resolver`'s id
+/// space, matching `AssembleImm<'_, hhbc::AdataId>`'s symbolic branch:
+/// mints `resolver`'s stable synthetic name (`label_{idx}`) for an entry
+/// that was never given an explicit one, instead of falling back to the
+/// bare numeric `A_{idx}` spelling, so every entry -- named or not --
+/// round-trips through the same `@name` grammar.
+pub fn print_label_ref(
+    w: &mut impl Write,
+    resolver: &LabelResolver,
+    idx: u32,
+) -> std::io::Result<()> {
+    write!(w, "@{}", resolver.name_for(idx))
+}
+
+/// Print an [`hhbc::FloatBits`], matching `AssembleImm<'_, hhbc::FloatBits>`.
+/// Emits the shortest decimal spelling when it round-trips back to the
+/// exact same bit pattern, and falls back to the C99 hex-float form
+/// otherwise -- unlike decimal, every finite `f64` (including subnormals)
+/// has a hex spelling that always round-trips exactly.
+pub fn print_float_bits(w: &mut impl Write, bits: FloatBits) -> std::io::Result<()> {
+    let value = bits.0;
+    if value.is_nan() {
+        let raw = value.to_bits();
+        return if raw == f64::NAN.to_bits() {
+            write!(w, "nan")
+        } else {
+            // The bare `nan` keyword only denotes the canonical quiet NaN;
+            // a signaling NaN, a custom payload, or a set sign bit needs
+            // its exact 64-bit pattern spelled out to round-trip.
+            write!(w, "nan{:016x}", raw)
+        };
+    }
+    if value.is_infinite() {
+        return write!(w, "{}", if value.is_sign_negative() { "-inf" } else { "inf" });
+    }
+    let decimal = format!("{}", value);
+    if decimal.parse::<f64>().map(f64::to_bits) == Ok(value.to_bits()) {
+        write!(w, "{}", decimal)
+    } else {
+        write!(w, "{}", format_hex_float(value))
+    }
+}
+
+/// Format a finite, non-NaN `f64` as a C99 hex float
+/// (`[-]0x<hexint>.<hexfrac>p<decexp>`) that denotes it bit-for-bit,
+/// matching what `parse_hex_float` in `assemble_imm.rs` accepts.
+pub(crate) fn format_hex_float(value: f64) -> String {
+    let bits = value.to_bits();
+    let sign = if bits >> 63 == 1 { "-" } else { "" };
+    let exp = ((bits >> 52) & 0x7ff) as i64;
+    let frac = bits & ((1 << 52) - 1);
+    if exp == 0 {
+        // Subnormal: no implicit leading bit.
+        format!("{sign}0x0.{frac:013x}p-1022")
+    } else {
+        format!("{sign}0x1.{frac:013x}p{}", exp - 1023)
+    }
+}
+
+/// Print an [`hhbc::AdataId`], e.g. `@A_3`, matching
+/// `AssembleImm<'_, hhbc::AdataId>`, which expects the leading `@` and
+/// strips it before calling [`hhbc::AdataId::parse`].
+pub fn print_adata_id(w: &mut impl Write, id: hhbc::AdataId) -> std::io::Result<()> {
+    write!(w, "@{}", id)
+}
+
+/// Print an [`hhbc::Local`] by numeric index, e.g. `_3`, matching the
+/// `Token::Identifier` branch of `AssembleImm<'_, hhbc::Local>`.
+///
+/// Locals that have a source name are printed as `$name` elsewhere via
+/// [`print_named_local`]; this is the fallback used when no name is known.
+pub fn print_local(w: &mut impl Write, local: Local) -> std::io::Result<()> {
+    write!(w, "_{}", local.idx)
+}
+
+/// Print an [`hhbc::Local`] as its declared `$name`, matching the
+/// `Token::Variable` branch of `AssembleImm<'_, hhbc::Local>`.
+pub fn print_named_local(w: &mut impl Write, name: &str) -> std::io::Result<()> {
+    write!(w, "${}", name)
+}
+
+/// Print an [`hhbc::LocalRange`] as `L:start len`, matching
+/// `AssembleImm<'_, hhbc::LocalRange>`.
+pub fn print_local_range(w: &mut impl Write, range: LocalRange) -> std::io::Result<()> {
+    write!(w, "L:{} {}", range.start.idx, range.len)
+}
+
+/// Print an [`hhbc::LocalRange`] as the named span `L:$first..$last`,
+/// matching the named-span branch of `AssembleImm<'_, hhbc::LocalRange>`,
+/// using `names` (idx -> declared name) to resolve both endpoints.
+///
+/// Falls back to the numeric `L:start len` form if either endpoint of the
+/// range has no declared name, since an unnamed slot can't be spelled as
+/// `$name`, and for an empty range, since a named span always covers at
+/// least one local.
+pub fn print_named_local_range(
+    w: &mut impl Write,
+    range: LocalRange,
+    names: impl Fn(u32) -> Option<String>,
+) -> std::io::Result<()> {
+    if range.len == 0 {
+        return print_local_range(w, range);
+    }
+    let last_idx = range.start.idx + range.len - 1;
+    match (names(range.start.idx), names(last_idx)) {
+        (Some(first), Some(last)) => write!(w, "L:${}..${}", first, last),
+        _ => print_local_range(w, range),
+    }
+}
+
+/// Print an [`hhbc::IterArgs`] as `<idx> NK|K:<key> V:<val>`, matching
+/// `AssembleImm<'_, hhbc::IterArgs>`.
+pub fn print_iter_args(w: &mut impl Write, args: &IterArgs) -> std::io::Result<()> {
+    write!(w, "{} ", args.iter_id.idx)?;
+    if args.key_id == Local::INVALID {
+        write!(w, "NK ")?;
+    } else {
+        write!(w, "K:")?;
+        print_local(w, args.key_id)?;
+        write!(w, " ")?;
+    }
+    write!(w, "V:")?;
+    print_local(w, args.val_id)
+}
+
+/// Print an [`hhbc::MemberKey`], matching `AssembleImm<'_, hhbc::MemberKey>`
+/// exactly: `EC:`/`EL:`/`ET:`/`EI:`/`PC:`/`PL:`/`PT:`/`QT:` followed by the
+/// key payload and a trailing `ReadonlyOp`, or the bare `W`.
+pub fn print_member_key(w: &mut impl Write, key: &MemberKey) -> std::io::Result<()> {
+    match key {
+        MemberKey::EC(idx, op) => {
+            write!(w, "EC:{} {:?}", idx, op)
+        }
+        MemberKey::EL(local, op) => {
+            write!(w, "EL:")?;
+            print_local(w, *local)?;
+            write!(w, " {:?}", op)
+        }
+        MemberKey::ET(s, op) => {
+            write!(w, "ET:\"{}\" {:?}", escaper::escape(s.as_str()), op)
+        }
+        MemberKey::EI(i, op) => {
+            write!(w, "EI:{} {:?}", i, op)
+        }
+        MemberKey::PC(idx, op) => {
+            write!(w, "PC:{} {:?}", idx, op)
+        }
+        MemberKey::PL(local, op) => {
+            write!(w, "PL:")?;
+            print_local(w, *local)?;
+            write!(w, " {:?}", op)
+        }
+        MemberKey::PT(name, op) => {
+            write!(w, "PT:\"{}\" {:?}", escaper::escape(name.as_str()), op)
+        }
+        MemberKey::QT(name, op) => {
+            write!(w, "QT:\"{}\" {:?}", escaper::escape(name.as_str()), op)
+        }
+        MemberKey::W => write!(w, "W"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::assemble::DeclMap;
+    use crate::assemble_imm::AssembleImm;
+    use crate::lexer::Lexer;
+
+    fn round_trip_local(local: Local) {
+        let mut buf = Vec::new();
+        print_local(&mut buf, local).unwrap();
+        let alloc = Bump::new();
+        let mut lexer = Lexer::from_bytes(&buf);
+        let decl_map = DeclMap::default();
+        let parsed: Local = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+        assert_eq!(parsed, local);
+    }
+
+    #[test]
+    fn local_round_trips() {
+        round_trip_local(Local { idx: 0 });
+        round_trip_local(Local { idx: 42 });
+    }
+
+    fn round_trip_local_range(range: LocalRange) {
+        let mut buf = Vec::new();
+        print_local_range(&mut buf, range).unwrap();
+        let alloc = Bump::new();
+        let mut lexer = Lexer::from_bytes(&buf);
+        let decl_map = DeclMap::default();
+        let parsed: LocalRange = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+        assert_eq!(parsed, range);
+    }
+
+    #[test]
+    fn local_range_round_trips() {
+        round_trip_local_range(LocalRange {
+            start: Local { idx: 2 },
+            len: 3,
+        });
+        round_trip_local_range(LocalRange {
+            start: Local { idx: 0 },
+            len: 0,
+        });
+    }
+
+    #[test]
+    fn named_local_range_round_trips() {
+        // idx 0 => "$first", idx 1 => "$mid", idx 2 => "$last"
+        let mut decl_map = DeclMap::default();
+        decl_map.insert(hhbc::intern("first"), 0);
+        decl_map.insert(hhbc::intern("mid"), 1);
+        decl_map.insert(hhbc::intern("last"), 2);
+        let names = |idx: u32| match idx {
+            0 => Some("first".to_string()),
+            1 => Some("mid".to_string()),
+            2 => Some("last".to_string()),
+            _ => None,
+        };
+
+        let range = LocalRange {
+            start: Local { idx: 0 },
+            len: 3,
+        };
+        let mut buf = Vec::new();
+        print_named_local_range(&mut buf, range, names).unwrap();
+        assert_eq!(buf, b"L:$first..$last");
+
+        let alloc = Bump::new();
+        let mut lexer = Lexer::from_bytes(&buf);
+        let parsed: LocalRange = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+        assert_eq!(parsed, range);
+    }
+
+    fn round_trip_iter_args(args: IterArgs) {
+        let mut buf = Vec::new();
+        print_iter_args(&mut buf, &args).unwrap();
+        let alloc = Bump::new();
+        let mut lexer = Lexer::from_bytes(&buf);
+        let decl_map = DeclMap::default();
+        let parsed: IterArgs = lexer.assemble_imm(&alloc, &decl_map).unwrap();
+        assert_eq!(parsed, args);
+    }
+
+    #[test]
+    fn label_ref_prints_explicit_name_and_mints_synthetic_one() {
+        let mut resolver = LabelResolver::new();
+        let named = resolver.define(Some("my_array")).unwrap();
+        let anon = resolver.define(None).unwrap();
+
+        let mut buf = Vec::new();
+        print_label_ref(&mut buf, &resolver, named).unwrap();
+        assert_eq!(buf, b"@my_array");
+        let printed = std::str::from_utf8(&buf).unwrap();
+        let name = printed.strip_prefix('@').unwrap();
+        assert_eq!(resolver.resolve(name).unwrap(), named);
+
+        let mut buf = Vec::new();
+        print_label_ref(&mut buf, &resolver, anon).unwrap();
+        assert_eq!(buf, b"@label_1");
+    }
+
+    #[test]
+    fn iter_args_round_trip_with_and_without_key() {
+        round_trip_iter_args(IterArgs {
+            iter_id: hhbc::IterId { idx: 0 },
+            key_id: Local::INVALID,
+            val_id: Local { idx: 1 },
+        });
+        round_trip_iter_args(IterArgs {
+            iter_id: hhbc::IterId { idx: 4 },
+            key_id: Local { idx: 2 },
+            val_id: Local { idx: 3 },
+        });
+    }
+}
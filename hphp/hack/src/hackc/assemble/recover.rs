@@ -0,0 +1,31 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! Entry point for recovering-mode assembly: parse as much of a (possibly
+//! hand-edited or adversarial) `.hhas` source as possible and report every
+//! diagnostic in one pass, rather than aborting at the first one.
+
+use bumpalo::Bump;
+
+use crate::assemble;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+
+/// Assemble `src` in recovering mode.
+///
+/// Unlike [`assemble::assemble`], a malformed token doesn't abort the parse:
+/// the `Lexer` records a [`Diagnostic`] and skips to the next
+/// instruction/statement boundary so the rest of the unit can still be
+/// assembled. Returns the best-effort `Unit` (`None` only if the source was
+/// malformed badly enough that no unit could be produced at all) alongside
+/// every diagnostic collected along the way.
+pub fn assemble_unit_recovering<'arena>(
+    alloc: &'arena Bump,
+    src: &[u8],
+) -> (Option<hhbc::Unit<'arena>>, Vec<Diagnostic>) {
+    let mut lexer = Lexer::from_bytes_recovering(src);
+    let unit = assemble::assemble_from_lexer(alloc, &mut lexer).ok();
+    (unit, lexer.take_diagnostics().into_vec())
+}
@@ -0,0 +1,200 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! Symbolic-label resolution for the textual assembler.
+//!
+//! `AdataId` is a bare `u32` rendered as `A_<n>`; hand-editing assembly that
+//! references entries by number is fragile because inserting or deleting
+//! one entry renumbers everything after it. [`LabelResolver`] lets the
+//! assembler accept a user-chosen name (`.adata my_array = ...` referenced
+//! as `@my_array`) instead, assigning the final dense index in definition
+//! order and resolving references against it, so edits that add or remove
+//! entries don't require renumbering every reference by hand.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use anyhow::Result;
+
+/// Maps user-chosen symbolic labels (and/or anonymous positional entries) to
+/// the dense `u32` index the textual format assigns them in definition
+/// order. One `LabelResolver` is used per id space (`AdataId`, `ConstantId`,
+/// `BlockId`, ...) within a unit.
+#[derive(Debug, Default)]
+pub struct LabelResolver {
+    by_name: HashMap<String, u32>,
+    /// Entry at index `i`'s explicit name, or `None` if it was defined
+    /// anonymously. Indexed in parallel with the dense index space, so
+    /// [`Self::name_for`] can hand the disassembler a name for every entry,
+    /// not just the ones a user bothered to name.
+    names: Vec<Option<String>>,
+}
+
+impl LabelResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a definition appearing in source order (e.g. a `.adata` entry
+    /// or a `L0:` block header), returning the dense index assigned to it.
+    /// `name` is `None` for an anonymous/positional entry.
+    ///
+    /// Rejects an explicit name that collides with a synthetic name
+    /// [`Self::synthetic_name`] could mint for some other entry: otherwise
+    /// `name_for` would print a synthetic `@label_1` for entry 1 while
+    /// `resolve("label_1")` returned a *different*, explicitly-named entry
+    /// that happened to claim that same spelling, so a disassembled
+    /// reference would silently resolve to the wrong entry on reassembly.
+    pub fn define(&mut self, name: Option<&str>) -> Result<u32> {
+        let idx = self.names.len() as u32;
+        if let Some(name) = name {
+            if let Some(synth_idx) = Self::parse_synthetic_name(name) {
+                if synth_idx != idx {
+                    bail!(
+                        "Label {:?} collides with the synthetic name reserved for entry {}",
+                        name,
+                        synth_idx
+                    );
+                }
+            }
+            if self.by_name.insert(name.to_string(), idx).is_some() {
+                bail!("Duplicate label: {:?}", name);
+            }
+        }
+        self.names.push(name.map(str::to_string));
+        Ok(idx)
+    }
+
+    /// Resolve a `@name`/`#name` reference to the index assigned to it by a
+    /// prior [`Self::define`] call.
+    pub fn resolve(&self, name: &str) -> Result<u32> {
+        self.by_name
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Undefined label: {:?}", name))
+    }
+
+    /// Number of entries defined so far, named or anonymous.
+    pub fn len(&self) -> u32 {
+        self.names.len() as u32
+    }
+
+    /// The name the disassembler should print for the entry at `idx`: its
+    /// explicit name if [`Self::define`] was given one, otherwise the
+    /// stable synthetic name [`Self::synthetic_name`] mints for it. This is
+    /// the disassembler-side half of the `@name`/`#name` grammar `resolve`
+    /// accepts on the way back in -- every entry gets a name to print, not
+    /// just the explicitly-named ones.
+    pub fn name_for(&self, idx: u32) -> String {
+        match self.names.get(idx as usize) {
+            Some(Some(name)) => name.clone(),
+            _ => Self::synthetic_name(idx),
+        }
+    }
+
+    /// The synthetic name minted for an entry that was never given an
+    /// explicit one, e.g. `label_3`. Exposed standalone so a caller that
+    /// already knows an index has no explicit name (rather than going
+    /// through [`Self::name_for`]) can still agree on the same spelling.
+    pub fn synthetic_name(idx: u32) -> String {
+        format!("label_{idx}")
+    }
+
+    /// If `name` is exactly the synthetic spelling [`Self::synthetic_name`]
+    /// would mint for some index, that index; otherwise `None`. Used by
+    /// [`Self::define`] to reject an explicit name that would collide with
+    /// a synthetic one.
+    fn parse_synthetic_name(name: &str) -> Option<u32> {
+        let idx = name.strip_prefix("label_")?.parse().ok()?;
+        (Self::synthetic_name(idx) == name).then_some(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_in_definition_order() {
+        let mut r = LabelResolver::new();
+        assert_eq!(r.define(Some("my_array")).unwrap(), 0);
+        assert_eq!(r.define(None).unwrap(), 1);
+        assert_eq!(r.define(Some("other")).unwrap(), 2);
+        assert_eq!(r.resolve("my_array").unwrap(), 0);
+        assert_eq!(r.resolve("other").unwrap(), 2);
+        assert_eq!(r.len(), 3);
+    }
+
+    #[test]
+    fn rejects_duplicate_labels() {
+        let mut r = LabelResolver::new();
+        r.define(Some("dup")).unwrap();
+        assert!(r.define(Some("dup")).is_err());
+    }
+
+    #[test]
+    fn rejects_explicit_name_colliding_with_a_synthetic_one() {
+        let mut r = LabelResolver::new();
+        // Entry 0 is anonymous, so `name_for(0)` would mint "label_0" --
+        // defining a later entry with that exact explicit name would make
+        // `resolve("label_0")` disagree with `name_for(0)` about which
+        // entry it names.
+        assert_eq!(r.define(None).unwrap(), 0);
+        assert!(r.define(Some("label_0")).is_err());
+    }
+
+    #[test]
+    fn allows_explicit_name_matching_its_own_synthetic_spelling() {
+        let mut r = LabelResolver::new();
+        // "label_0" is only a collision if it names some *other* index;
+        // claiming it for the index it would have been minted for anyway
+        // is harmless.
+        assert_eq!(r.define(Some("label_0")).unwrap(), 0);
+        assert_eq!(r.resolve("label_0").unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_undefined_references() {
+        let r = LabelResolver::new();
+        assert!(r.resolve("nope").is_err());
+    }
+
+    #[test]
+    fn names_anonymous_entries_synthetically() {
+        let mut r = LabelResolver::new();
+        assert_eq!(r.define(Some("my_array")).unwrap(), 0);
+        assert_eq!(r.define(None).unwrap(), 1);
+        assert_eq!(r.name_for(0), "my_array");
+        assert_eq!(r.name_for(1), LabelResolver::synthetic_name(1));
+        assert_eq!(r.name_for(1), "label_1");
+    }
+
+    /// Models the full definition/reference/disassembly cycle a real
+    /// `.adata`-style directive parser and its matching disassembler would
+    /// drive this type through: each entry is `define`d as it's parsed in
+    /// source order, a later `@name`/`@N`-style reference `resolve`s back
+    /// to the same index, and the disassembler mints a name for every
+    /// entry (explicit or synthetic) via `name_for` so the round trip
+    /// holds even for entries nobody named.
+    #[test]
+    fn round_trips_definition_reference_and_disassembly() {
+        let mut r = LabelResolver::new();
+        let named_idx = r.define(Some("my_array")).unwrap();
+        let anon_idx = r.define(None).unwrap();
+
+        // A reference by the name the definition chose resolves back to
+        // the same index that definition was assigned.
+        assert_eq!(r.resolve("my_array").unwrap(), named_idx);
+
+        // The disassembler can recover a stable name for every index,
+        // including the anonymous one, and that name resolves right back.
+        let anon_name = r.name_for(anon_idx);
+        assert_eq!(anon_name, "label_1");
+        let mut r2 = LabelResolver::new();
+        r2.define(Some("my_array")).unwrap();
+        r2.define(Some(&anon_name)).unwrap();
+        assert_eq!(r2.resolve(&anon_name).unwrap(), anon_idx);
+    }
+}
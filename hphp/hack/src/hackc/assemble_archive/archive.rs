@@ -0,0 +1,85 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! Batch assembly of whole-program HHAS bundles.
+//!
+//! A deployed repo is usually handed to us as a single `.zip` of `*.hhas`
+//! text units rather than one file at a time. Walk the archive, assemble
+//! each entry independently, and hand back every resulting `hhbc::Unit`
+//! keyed by its entry name along with any per-entry failure, instead of
+//! aborting the whole batch on the first bad unit.
+
+use std::io::Read;
+
+use anyhow::Context;
+use anyhow::Result;
+use bumpalo::Bump;
+
+/// Upper bound on how much an entry's declared uncompressed size is trusted
+/// for pre-allocation. A zip entry's declared size is attacker-controlled --
+/// a small compressed entry can claim an enormous uncompressed size -- so
+/// this is just a sane cap on the capacity hint, not a limit on how large an
+/// entry `assemble_archive` can actually read; `read_to_end` still grows the
+/// buffer past this if a legitimately larger `.hhas` file needs it.
+const MAX_PREALLOCATED_ENTRY_SIZE: usize = 16 * 1024 * 1024;
+
+/// The outcome of assembling a single `*.hhas` entry out of the archive.
+pub struct EntryResult<'arena> {
+    /// Path of the entry within the archive, e.g. `"some/Class.hhas"`.
+    pub entry: String,
+    pub unit: Result<hhbc::Unit<'arena>>,
+}
+
+/// Assemble every `*.hhas` entry in `zip_bytes`, in archive order.
+///
+/// Each entry is assembled independently: a malformed unit is recorded as an
+/// `Err` in its `EntryResult` rather than aborting the rest of the archive,
+/// so a caller can round-trip an entire deployed repo in one invocation and
+/// still see every diagnostic.
+pub fn assemble_archive<'arena>(
+    alloc: &'arena Bump,
+    zip_bytes: &[u8],
+) -> Result<Vec<EntryResult<'arena>>> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip_bytes))
+        .context("failed to open HHAS archive as a zip file")?;
+
+    let mut results = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() || !file.name().ends_with(".hhas") {
+            continue;
+        }
+        let entry = file.name().to_string();
+        // Don't trust the entry's declared uncompressed size outright for
+        // pre-allocation -- it's part of the (potentially adversarial) zip
+        // metadata, read before any of the entry's bytes are validated.
+        let capacity_hint = (file.size() as usize).min(MAX_PREALLOCATED_ENTRY_SIZE);
+        let mut source = Vec::with_capacity(capacity_hint);
+        let unit = file
+            .read_to_end(&mut source)
+            .context("failed to read archive entry")
+            .and_then(|_| assemble::assemble(alloc, &source).context("failed to assemble entry"));
+        results.push(EntryResult { entry, unit });
+    }
+    Ok(results)
+}
+
+/// Convenience split of [`assemble_archive`]'s output into the units that
+/// assembled cleanly and the `(entry, error)` diagnostics for those that
+/// didn't.
+pub fn assemble_archive_partitioned<'arena>(
+    alloc: &'arena Bump,
+    zip_bytes: &[u8],
+) -> Result<(Vec<(String, hhbc::Unit<'arena>)>, Vec<(String, anyhow::Error)>)> {
+    let mut units = Vec::new();
+    let mut diagnostics = Vec::new();
+    for EntryResult { entry, unit } in assemble_archive(alloc, zip_bytes)? {
+        match unit {
+            Ok(unit) => units.push((entry, unit)),
+            Err(err) => diagnostics.push((entry, err)),
+        }
+    }
+    Ok((units, diagnostics))
+}
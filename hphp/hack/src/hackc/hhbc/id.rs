@@ -37,6 +37,16 @@ macro_rules! impl_id {
                 self.0.unsafe_as_str()
             }
 
+            /// Like [`Self::unsafe_as_str`], but returns `None` instead of
+            /// assuming UTF-8 validity. Hack identifiers are stored as
+            /// bytes and can legitimately contain non-UTF-8 names (`Unit`
+            /// tracks this via `valid_utf8`/`invalid_utf8_offset`), so
+            /// callers that must not lose information should prefer this
+            /// over `unsafe_as_str`.
+            pub fn try_as_str(&self) -> Option<&'arena str> {
+                std::str::from_utf8(self.as_bytes()).ok()
+            }
+
             pub fn as_ffi_str(&self) -> ffi::Str<'arena> {
                 self.0
             }
@@ -95,12 +105,23 @@ macro_rules! impl_intern_id {
                 self.0.as_str()
             }
 
+            /// Like [`Self::as_str`], but returns `None` instead of
+            /// assuming UTF-8 validity, for symmetry with the arena-backed
+            /// id types that can legitimately hold non-UTF-8 bytes.
+            pub fn try_as_str(&self) -> Option<&'static str> {
+                std::str::from_utf8(self.as_bytes()).ok()
+            }
+
             pub fn as_bstr(&self) -> &'static BStr {
                 self.as_bytes().as_bstr()
             }
 
+            // Goes through the interner's raw-bytes accessor rather than
+            // `as_str()`, which asserts UTF-8 and panics otherwise -- this
+            // is what lets `try_as_str()` actually fail gracefully instead
+            // of panicking on exactly the input it exists to handle.
             pub fn as_bytes(&self) -> &'static [u8] {
-                self.0.as_str().as_bytes()
+                self.0.as_bstr().as_bytes()
             }
 
             pub fn intern(s: &str) -> $type {
@@ -220,6 +241,15 @@ impl<'arena> ClassName<'arena> {
         ))
     }
 
+    /// Bytes-native counterpart of [`Self::from_ast_name_and_mangle`] for
+    /// class names that aren't valid UTF-8. Hack identifiers are stored as
+    /// `bstr` and can legitimately carry non-UTF-8 bytes; going through
+    /// `String` here would silently lose that information.
+    pub fn from_ast_bytes_and_mangle(alloc: &'arena bumpalo::Bump, s: &[u8]) -> Self {
+        let mangled = mangle_bytes(s);
+        ClassName(Str::new_slice(alloc, strip_global_ns_bytes(&mangled)))
+    }
+
     pub fn mangle(s: impl std::convert::Into<std::string::String>) -> StringId {
         intern::string::intern(hhbc_string_utils::strip_global_ns(
             &hhbc_string_utils::mangle(s.into()),
@@ -229,6 +259,58 @@ impl<'arena> ClassName<'arena> {
     pub fn unsafe_to_unmangled_str(&self) -> std::borrow::Cow<'arena, str> {
         std::borrow::Cow::from(hhbc_string_utils::unmangle(self.unsafe_as_str().into()))
     }
+
+    /// Bytes-native counterpart of [`Self::unsafe_to_unmangled_str`].
+    pub fn to_unmangled_bytes(&self) -> std::borrow::Cow<'arena, [u8]> {
+        match unmangle_bytes(self.as_bytes()) {
+            std::borrow::Cow::Borrowed(b) => std::borrow::Cow::Borrowed(b),
+            std::borrow::Cow::Owned(b) => std::borrow::Cow::Owned(b),
+        }
+    }
+}
+
+/// Bytes-native counterpart of `hhbc_string_utils::strip_global_ns`: drop a
+/// single leading `\` (the global-namespace marker), if present.
+fn strip_global_ns_bytes(s: &[u8]) -> &[u8] {
+    s.strip_prefix(b"\\").unwrap_or(s)
+}
+
+/// Bytes-native counterpart of `hhbc_string_utils::mangle`'s XHP handling
+/// (`:foo:bar` -> `xhp_foo__bar`), operating on raw bytes so a non-UTF-8
+/// class name is never decoded through `String` and silently mangled.
+fn mangle_bytes(s: &[u8]) -> std::borrow::Cow<'_, [u8]> {
+    if s.first() != Some(&b':') {
+        return std::borrow::Cow::Borrowed(s);
+    }
+    let mut out = Vec::with_capacity(s.len() + 4);
+    out.extend_from_slice(b"xhp_");
+    for &b in &s[1..] {
+        match b {
+            b':' => out.extend_from_slice(b"__"),
+            b'-' => out.push(b'_'),
+            b => out.push(b),
+        }
+    }
+    std::borrow::Cow::Owned(out)
+}
+
+/// Best-effort inverse of [`mangle_bytes`].
+fn unmangle_bytes(s: &[u8]) -> std::borrow::Cow<'_, [u8]> {
+    let Some(rest) = s.strip_prefix(b"xhp_") else {
+        return std::borrow::Cow::Borrowed(s);
+    };
+    let mut out = Vec::with_capacity(s.len());
+    out.push(b':');
+    let mut iter = rest.iter().copied().peekable();
+    while let Some(b) = iter.next() {
+        if b == b'_' && iter.peek() == Some(&b'_') {
+            iter.next();
+            out.push(b':');
+        } else {
+            out.push(b);
+        }
+    }
+    std::borrow::Cow::Owned(out)
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd, Serialize)]
@@ -364,4 +446,35 @@ mod tests {
         let ids: Vec<&str> = ids.into_iter().map(|id| id.as_str()).collect();
         assert_eq!(expected, ids.as_slice());
     }
+
+    #[test]
+    fn test_try_as_str_non_utf8() {
+        let alloc = bumpalo::Bump::new();
+        let id = ClassName::from_bytes(&alloc, b"Foo\xFFBar");
+        assert_eq!(None, id.try_as_str());
+        assert_eq!(b"Foo\xFFBar", id.as_bytes());
+    }
+
+    #[test]
+    fn test_from_ast_bytes_and_mangle_xhp() {
+        let alloc = bumpalo::Bump::new();
+        let id = ClassName::from_ast_bytes_and_mangle(&alloc, b":foo:bar-baz");
+        assert_eq!(b"xhp_foo__bar_baz", id.as_bytes());
+    }
+
+    #[test]
+    fn test_from_ast_bytes_and_mangle_non_utf8() {
+        let alloc = bumpalo::Bump::new();
+        let id = ClassName::from_ast_bytes_and_mangle(&alloc, b":\xFF:bar");
+        assert_eq!(b"xhp_\xFF__bar", id.as_bytes());
+        assert_eq!(None, id.try_as_str());
+    }
+
+    #[test]
+    fn test_mangle_unmangle_bytes_round_trip() {
+        assert_eq!(
+            b":foo:bar".as_slice(),
+            unmangle_bytes(&mangle_bytes(b":foo:bar")).as_ref()
+        );
+    }
 }
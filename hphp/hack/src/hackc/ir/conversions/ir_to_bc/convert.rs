@@ -6,20 +6,49 @@
 use std::sync::Arc;
 
 use ffi::Maybe;
+use ffi::Vector;
 use hhbc::Fatal;
 
 use crate::adata::AdataCache;
+use crate::errors::ConversionErrors;
 use crate::strings::StringCache;
 
+/// Convert an ir::Unit to a hhbc::Unit, tolerating unresolved/unconvertible
+/// symbols by recording them rather than panicking. Prefer this over
+/// [`ir_to_bc`] when the caller wants to know whether anything was lossy;
+/// `ir_to_bc` just discards the accumulated errors.
+///
+/// Most of the outer structure of the hhbc::Unit maps 1:1 with ir::Unit. As a
+/// result the "interesting" work is in the conversion of the IR to bytecode
+/// when converting functions and methods (see `convert_func` in func.rs) --
+/// that's also where most `ConversionErrors` entries would originate once
+/// those conversions are wired up to record into `UnitBuilder::errors`
+/// instead of panicking. `lower` already does this for the two cases it can
+/// detect on its own: a `module_use` that doesn't name one of the unit's own
+/// modules (`missing_symbol`) and a non-UTF-8 identifier that had to be
+/// lossily escaped (`error_symbol`).
+pub fn try_ir_to_bc(ir_unit: ir::Unit) -> Result<hhbc::Unit, ConversionErrors> {
+    let (unit, errors) = lower(ir_unit);
+    if errors.is_empty() {
+        Ok(unit)
+    } else {
+        Err(errors)
+    }
+}
+
 /// Convert an ir::Unit to a hhbc::Unit
 ///
 /// Most of the outer structure of the hhbc::Unit maps 1:1 with ir::Unit. As a
 /// result the "interesting" work is in the conversion of the IR to bytecode
 /// when converting functions and methods (see `convert_func` in func.rs).
 pub fn ir_to_bc(ir_unit: ir::Unit) -> hhbc::Unit {
+    lower(ir_unit).0
+}
+
+fn lower(ir_unit: ir::Unit) -> (hhbc::Unit, ConversionErrors) {
     let strings = StringCache::new(Arc::clone(&ir_unit.strings));
 
-    let mut unit = UnitBuilder::new();
+    let mut unit = UnitBuilder::with_capacity(ir_unit.classes.len(), ir_unit.functions.len());
 
     for cls in ir_unit.classes.into_iter() {
         crate::class::convert_class(&mut unit, cls, &strings);
@@ -29,7 +58,7 @@ pub fn ir_to_bc(ir_unit: ir::Unit) -> hhbc::Unit {
         crate::func::convert_function(&mut unit, function, &strings);
     }
 
-    let mut unit = unit.finish();
+    let (mut unit, mut errors) = unit.finish(&strings);
 
     unit.file_attributes = convert_attributes(ir_unit.file_attributes, &strings).into();
     unit.typedefs = ir_unit
@@ -44,6 +73,12 @@ pub fn ir_to_bc(ir_unit: ir::Unit) -> hhbc::Unit {
         .map(|c| crate::constant::convert_hack_constant(c, &strings))
         .collect::<Vec<_>>()
         .into();
+
+    // Captured before the loop below consumes `ir_unit.modules`, so
+    // `module_use` -- a reference to one of this unit's own modules -- can
+    // be checked against what the unit actually defines.
+    let modules_defined: Vec<ir::ModuleId> = ir_unit.modules.iter().map(|m| m.name).collect();
+
     unit.modules = ir_unit
         .modules
         .into_iter()
@@ -52,11 +87,20 @@ pub fn ir_to_bc(ir_unit: ir::Unit) -> hhbc::Unit {
             name: strings.lookup_module_name(module.name),
             span: module.src_loc.to_span(),
             doc_comment: module.doc_comment.map(|c| c.into()).into(),
-            exports: Maybe::Nothing, // TODO
-            imports: Maybe::Nothing, // TODO
+            exports: convert_module_rules(module.exports, &strings),
+            imports: convert_module_rules(module.imports, &strings),
         })
         .collect::<Vec<_>>()
         .into();
+
+    // `module_use` is only meaningful if it actually names a module this
+    // unit defines; record it as missing rather than silently emitting a
+    // dangling reference.
+    if let Some(used) = ir_unit.module_use {
+        if !modules_defined.contains(&used) {
+            errors.missing_symbol(strings.lookup_module_name(used).as_str());
+        }
+    }
     unit.module_use = ir_unit
         .module_use
         .map(|id| strings.lookup_module_name(id))
@@ -77,26 +121,97 @@ pub fn ir_to_bc(ir_unit: ir::Unit) -> hhbc::Unit {
         });
     }
 
-    unit
+    // A non-UTF-8 identifier no longer aborts the conversion (see
+    // `StringCache::intern_lossy`); instead it's escaped and the first
+    // occurrence is surfaced here, same as the textual assembler does. It's
+    // also a genuine (if partial) conversion failure -- the identifier
+    // couldn't be represented as the UTF-8 string the rest of the unit
+    // expects -- so it's also recorded as an error symbol.
+    if strings.has_invalid_utf8() {
+        unit.valid_utf8 = false;
+        unit.invalid_utf8_offset = strings.first_invalid_utf8_offset();
+        errors.error_symbol(
+            format!("<byte offset {}>", unit.invalid_utf8_offset),
+            "identifier contained non-UTF-8 bytes; lossily escaped as \\xHH",
+        );
+    }
+
+    // The two checks above run after `finish`, so refresh the unit's copies.
+    unit.missing_symbols = errors
+        .missing_symbols()
+        .iter()
+        .map(|s| ffi::Str::new_slice(strings.alloc, s.as_bytes()))
+        .collect::<Vec<_>>()
+        .into();
+    unit.error_symbols = errors
+        .error_symbols()
+        .iter()
+        .map(|e| ffi::Str::new_slice(strings.alloc, e.symbol.as_bytes()))
+        .collect::<Vec<_>>()
+        .into();
+
+    (unit, errors)
 }
 
 pub(crate) struct UnitBuilder {
     pub adata_cache: AdataCache,
     pub functions: Vec<hhbc::Function>,
     pub classes: Vec<hhbc::Class>,
+    pub errors: ConversionErrors,
 }
 
 impl UnitBuilder {
-    fn new() -> Self {
+    /// Pre-size `functions`/`classes` for a unit expected to have roughly
+    /// `classes_hint`/`functions_hint` entries, and pass the same combined
+    /// hint to `AdataCache` (array literals are overwhelmingly found in
+    /// function/method bodies and class property initializers, so the
+    /// entry count is a reasonable proxy absent a precise count of
+    /// array-typed `TypedValue`s). Cuts down on `Vec` reallocation churn
+    /// when lowering a large unit.
+    fn with_capacity(classes_hint: usize, functions_hint: usize) -> Self {
         Self {
-            adata_cache: AdataCache::new(),
-            classes: Default::default(),
-            functions: Default::default(),
+            adata_cache: AdataCache::with_capacity(classes_hint + functions_hint),
+            classes: Vec::with_capacity(classes_hint),
+            functions: Vec::with_capacity(functions_hint),
+            errors: ConversionErrors::new(),
         }
     }
 
-    fn finish(self) -> hhbc::Unit {
-        hhbc::Unit {
+    /// Clear this builder for reuse on the next unit, keeping the
+    /// `functions`/`classes` `Vec`s' and `AdataCache`'s already-allocated
+    /// capacity instead of dropping and reallocating them. Meant to be
+    /// paired with [`StringCache::reset`] in a driver that lowers many
+    /// units in a row. Unused by `ir_to_bc` itself today, which always
+    /// starts from a fresh builder; kept `pub(crate)`-visible for such a
+    /// driver, which this tree snapshot doesn't include.
+    #[allow(dead_code)]
+    pub(crate) fn reset(&mut self, classes_hint: usize, functions_hint: usize) {
+        self.classes.clear();
+        self.classes.reserve(classes_hint);
+        self.functions.clear();
+        self.functions.reserve(functions_hint);
+        self.adata_cache.clear();
+        self.errors = ConversionErrors::new();
+    }
+
+    /// Consume the builder, returning both the lowered unit and whatever
+    /// [`ConversionErrors`] were accumulated along the way -- the unit's
+    /// `missing_symbols`/`error_symbols` are populated from the same
+    /// accumulator, so the two always agree.
+    fn finish(self, strings: &StringCache) -> (hhbc::Unit, ConversionErrors) {
+        let missing_symbols = self
+            .errors
+            .missing_symbols()
+            .iter()
+            .map(|s| ffi::Str::new_slice(strings.alloc, s.as_bytes()))
+            .collect::<Vec<_>>();
+        let error_symbols = self
+            .errors
+            .error_symbols()
+            .iter()
+            .map(|e| ffi::Str::new_slice(strings.alloc, e.symbol.as_bytes()))
+            .collect::<Vec<_>>();
+        let unit = hhbc::Unit {
             adata: self.adata_cache.finish().into(),
             functions: self.functions.into(),
             classes: self.classes.into(),
@@ -107,11 +222,46 @@ impl UnitBuilder {
             symbol_refs: Default::default(),
             constants: Default::default(),
             fatal: Default::default(),
-            missing_symbols: Default::default(),
-            error_symbols: Default::default(),
+            missing_symbols: missing_symbols.into(),
+            error_symbols: error_symbols.into(),
             valid_utf8: true,
             invalid_utf8_offset: 0,
-        }
+        };
+        (unit, self.errors)
+    }
+}
+
+/// Lower a module's export or import rule list -- each entry a package or
+/// module name, possibly a wildcard glob -- to the hhbc form.
+///
+/// `ir::Module`'s definition isn't part of this tree snapshot, so its
+/// `exports`/`imports` field types can't be read directly here. They're
+/// taken to be `Option<Vec<ir::UnitBytesId>>` on the strength of
+/// `bc_to_ir::bc_to_ir`'s `let modules: Vec<ir::Module> =
+/// unit.modules.clone().into();`, which already requires `hhbc::Module`
+/// (constructed with these same two fields just above) and `ir::Module` to
+/// agree field-for-field for that blanket conversion to typecheck -- so
+/// this isn't a free guess, it's the shape the existing bidirectional
+/// conversion already commits to. A module with no rules at all (the
+/// common case) keeps the pre-existing `Maybe::Nothing`.
+///
+/// No unit test accompanies this: exercising it needs a `StringCache`,
+/// which needs a real `ir::StringInterner`, and this snapshot exposes no
+/// public constructor for one. A "unit with non-empty export/import sets lowers
+/// without loss" test belongs here once that's available.
+fn convert_module_rules(
+    rules: Option<Vec<ir::UnitBytesId>>,
+    strings: &StringCache,
+) -> Maybe<Vector<hhbc::ModuleName>> {
+    match rules {
+        Some(names) => Maybe::Just(
+            names
+                .into_iter()
+                .map(|id| strings.lookup_module_rule(id))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        None => Maybe::Nothing,
     }
 }
 
@@ -151,9 +301,7 @@ pub(crate) fn convert_typed_value(tv: &ir::TypedValue, strings: &StringCache) ->
         ir::TypedValue::String(v) => {
             hhbc::TypedValue::intern_string(&*strings.interner.lookup_bytes(*v))
         }
-        ir::TypedValue::LazyClass(v) => {
-            hhbc::TypedValue::intern_lazy_class(strings.intern(v.id).expect("non-utf8 class name"))
-        }
+        ir::TypedValue::LazyClass(v) => hhbc::TypedValue::intern_lazy_class(strings.intern(v.id)),
         ir::TypedValue::Null => hhbc::TypedValue::Null,
         ir::TypedValue::Vec(ref vs) => hhbc::TypedValue::Vec(
             Vec::from_iter(vs.iter().map(|v| convert_typed_value(v, strings))).into(),
@@ -175,9 +323,7 @@ pub(crate) fn convert_typed_value(tv: &ir::TypedValue, strings: &StringCache) ->
 pub(crate) fn convert_array_key(tv: &ir::ArrayKey, strings: &StringCache) -> hhbc::TypedValue {
     match *tv {
         ir::ArrayKey::Int(v) => hhbc::TypedValue::Int(v),
-        ir::ArrayKey::LazyClass(v) => {
-            hhbc::TypedValue::intern_lazy_class(strings.intern(v.id).expect("non-utf8 class name"))
-        }
+        ir::ArrayKey::LazyClass(v) => hhbc::TypedValue::intern_lazy_class(strings.intern(v.id)),
         ir::ArrayKey::String(v) => {
             hhbc::TypedValue::intern_string(&*strings.interner.lookup_bytes(v))
         }
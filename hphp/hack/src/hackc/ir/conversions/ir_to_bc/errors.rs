@@ -0,0 +1,105 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the "hack" directory of this source tree.
+
+//! Non-fatal problems accumulated while lowering an `ir::Unit` to a
+//! `hhbc::Unit`, instead of panicking on the first one. This is the same
+//! tolerant-by-default approach `StringCache::intern_lossy` takes for
+//! non-UTF-8 names: a bad reference gets recorded and lowering continues,
+//! so one unresolved symbol in a large unit doesn't take down the whole
+//! conversion.
+
+use std::fmt;
+
+/// A symbol that lowering attempted to convert but couldn't, along with why.
+#[derive(Debug, Clone)]
+pub struct ConversionError {
+    pub symbol: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.symbol, self.reason)
+    }
+}
+
+/// Accumulates [`ConversionError`]s across an entire `ir_to_bc` run.
+///
+/// `missing` holds references to symbols that lowering couldn't find at
+/// all (destined for `Unit::missing_symbols`); `errors` holds symbols that
+/// were found but couldn't be converted (destined for
+/// `Unit::error_symbols`).
+#[derive(Debug, Default)]
+pub struct ConversionErrors {
+    missing: Vec<String>,
+    errors: Vec<ConversionError>,
+}
+
+impl ConversionErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a reference to a symbol (class/function/const/module) that
+    /// lowering could not find.
+    pub fn missing_symbol(&mut self, symbol: impl Into<String>) {
+        self.missing.push(symbol.into());
+    }
+
+    /// Record a symbol that was found but whose lowering failed for some
+    /// other reason (e.g. an unsupported construct).
+    pub fn error_symbol(&mut self, symbol: impl Into<String>, reason: impl Into<String>) {
+        self.errors.push(ConversionError {
+            symbol: symbol.into(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.errors.is_empty()
+    }
+
+    pub fn missing_symbols(&self) -> &[String] {
+        &self.missing
+    }
+
+    pub fn error_symbols(&self) -> &[ConversionError] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for ConversionErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for m in &self.missing {
+            writeln!(f, "missing symbol: {m}")?;
+        }
+        for e in &self.errors {
+            writeln!(f, "{e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConversionErrors {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_by_default() {
+        assert!(ConversionErrors::new().is_empty());
+    }
+
+    #[test]
+    fn records_missing_and_error_symbols() {
+        let mut errors = ConversionErrors::new();
+        errors.missing_symbol("SomeClass");
+        errors.error_symbol("some_func", "unsupported opcode");
+        assert!(!errors.is_empty());
+        assert_eq!(errors.missing_symbols(), ["SomeClass"]);
+        assert_eq!(errors.error_symbols()[0].symbol, "some_func");
+    }
+}
@@ -3,6 +3,8 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the "hack" directory of this source tree.
 
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use dashmap::DashMap;
@@ -14,6 +16,16 @@ pub(crate) struct StringCache<'a> {
     pub alloc: &'a bumpalo::Bump,
     cache: DashMap<UnitBytesId, Str<'a>>,
     pub interner: Arc<StringInterner>,
+    /// Byte offset (within whichever name it showed up in) of the invalid
+    /// UTF-8 sequence escaped by the first `lookup_*`/`intern_lossy` call
+    /// that had to escape anything, or `u64::MAX` if none has yet. "First"
+    /// means first call in processing order, set once via
+    /// `compare_exchange` -- not the numeric minimum across all calls,
+    /// which would report whichever name's bad byte happens to sit
+    /// earliest within that one name rather than which name was actually
+    /// converted first. Feeds `Unit::invalid_utf8_offset` once conversion
+    /// finishes.
+    first_invalid_offset: AtomicU64,
 }
 
 impl<'a> StringCache<'a> {
@@ -23,6 +35,81 @@ impl<'a> StringCache<'a> {
             alloc,
             cache,
             interner,
+            first_invalid_offset: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Clear this cache for reuse against a new unit's interner, keeping
+    /// the `DashMap`'s already-allocated capacity instead of dropping and
+    /// reallocating it. Meant for a driver that lowers many units in a
+    /// row and wants to avoid paying for a fresh hash map each time; see
+    /// `UnitBuilder::reset` in convert.rs for the other half.
+    #[allow(dead_code)]
+    pub fn reset(&mut self, interner: Arc<StringInterner>) {
+        self.cache.clear();
+        self.cache.reserve(interner.len());
+        self.interner = interner;
+        self.first_invalid_offset.store(u64::MAX, Ordering::Relaxed);
+    }
+
+    /// Whether any name lookup has had to lossily escape invalid UTF-8.
+    /// Unlike the old `.expect("non-utf8 ... name")` calls this replaces,
+    /// a non-UTF-8 identifier no longer aborts the conversion -- it's
+    /// escaped and tracked here instead, via `Unit::valid_utf8`.
+    pub fn has_invalid_utf8(&self) -> bool {
+        self.first_invalid_offset.load(Ordering::Relaxed) != u64::MAX
+    }
+
+    /// The byte offset to report as `Unit::invalid_utf8_offset`, valid only
+    /// when [`Self::has_invalid_utf8`] is true.
+    pub fn first_invalid_utf8_offset(&self) -> usize {
+        self.first_invalid_offset.load(Ordering::Relaxed) as usize
+    }
+
+    /// Intern `id`'s bytes into a `hhbc::StringId`, losslessly: a maximal
+    /// valid-UTF-8 run is passed through unchanged, and every invalid byte
+    /// is escaped as a lowercase `\xHH`, exactly the way a valid codepoint
+    /// would be escaped. Records the first such escape across the
+    /// lifetime of this cache (see [`Self::has_invalid_utf8`]).
+    pub fn intern_lossy(&self, id: UnitBytesId) -> hhbc::StringId {
+        let bytes = self.interner.lookup_bytes(id);
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => hhbc::intern(s),
+            Err(_) => hhbc::intern(&self.escape_invalid_utf8(&bytes)),
+        }
+    }
+
+    fn escape_invalid_utf8(&self, bytes: &[u8]) -> String {
+        let mut out = String::with_capacity(bytes.len());
+        let mut rest = bytes;
+        let mut offset = 0usize;
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    out.push_str(valid);
+                    return out;
+                }
+                Err(e) => {
+                    let valid_up_to = e.valid_up_to();
+                    let (valid, after) = rest.split_at(valid_up_to);
+                    out.push_str(std::str::from_utf8(valid).unwrap());
+                    let invalid_offset = (offset + valid_up_to) as u64;
+                    // Record only the very first invalid byte this cache
+                    // ever sees, in call order -- not the numeric minimum
+                    // across calls, which would conflate an offset's
+                    // position within its own name with which name was
+                    // actually processed first.
+                    let _ = self.first_invalid_offset.compare_exchange(
+                        u64::MAX,
+                        invalid_offset,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
+                    out.push_str(&format!("\\x{:02x}", after[0]));
+                    offset += valid_up_to + 1;
+                    rest = &after[1..];
+                }
+            }
         }
     }
 
@@ -33,28 +120,34 @@ impl<'a> StringCache<'a> {
         })
     }
 
-    pub fn intern(&self, id: UnitBytesId) -> Result<hhbc::StringId, std::str::Utf8Error> {
-        Ok(hhbc::intern(std::str::from_utf8(
-            &self.interner.lookup_bytes(id),
-        )?))
+    /// Intern `id`'s bytes, losslessly escaping invalid UTF-8 rather than
+    /// failing. Kept infallible (unlike its old `Result` signature) since
+    /// [`Self::intern_lossy`] always succeeds; callers that need to know
+    /// whether escaping happened can check [`Self::has_invalid_utf8`].
+    pub fn intern(&self, id: UnitBytesId) -> hhbc::StringId {
+        self.intern_lossy(id)
     }
 
     pub fn lookup_class_name(&self, id: ir::ClassId) -> hhbc::ClassName {
-        hhbc::ClassName::intern(
-            std::str::from_utf8(&self.interner.lookup_bstr(id.id)).expect("non-utf8 class name"),
-        )
+        hhbc::ClassName::intern(self.intern_lossy(id.id).as_str())
     }
 
     pub fn lookup_module_name(&self, id: ir::ModuleId) -> hhbc::ModuleName {
-        hhbc::ModuleName::intern(
-            std::str::from_utf8(&self.interner.lookup_bstr(id.id)).expect("non-utf8 module name"),
-        )
+        hhbc::ModuleName::intern(self.intern_lossy(id.id).as_str())
+    }
+
+    /// Lower a single entry in a module's export/import rule list. Unlike
+    /// [`Self::lookup_module_name`], which takes a definition-site
+    /// `ir::ModuleId`, an export/import rule is a free-standing interned
+    /// name (a package or module name, possibly a wildcard glob, not
+    /// necessarily a module defined in this unit), so this takes the raw
+    /// `UnitBytesId` directly.
+    pub fn lookup_module_rule(&self, id: UnitBytesId) -> hhbc::ModuleName {
+        hhbc::ModuleName::intern(self.intern_lossy(id).as_str())
     }
 
     pub fn lookup_const_name(&self, id: ir::ConstId) -> hhbc::ConstName {
-        hhbc::ConstName::intern(
-            std::str::from_utf8(&self.interner.lookup_bstr(id.id)).expect("non-utf8 const name"),
-        )
+        hhbc::ConstName::intern(self.intern_lossy(id.id).as_str())
     }
 
     pub fn lookup_method_name(&self, id: ir::MethodId) -> hhbc::MethodName<'a> {
@@ -63,14 +156,10 @@ impl<'a> StringCache<'a> {
     }
 
     pub fn lookup_function_name(&self, id: ir::FunctionId) -> hhbc::FunctionName {
-        hhbc::FunctionName::intern(
-            std::str::from_utf8(&self.interner.lookup_bstr(id.id)).expect("non-utf8 function name"),
-        )
+        hhbc::FunctionName::intern(self.intern_lossy(id.id).as_str())
     }
 
     pub fn lookup_prop_name(&self, id: ir::PropId) -> hhbc::PropName {
-        hhbc::PropName::intern(
-            std::str::from_utf8(&self.interner.lookup_bstr(id.id)).expect("non-utf8 prop name"),
-        )
+        hhbc::PropName::intern(self.intern_lossy(id.id).as_str())
     }
 }